@@ -46,7 +46,8 @@
 //! ### `use_tlv`
 //!
 //! Applies TLV extension to the data type and allows use of `tlv` and
-//! `unknown_tlvs` arguments on struct fields.
+//! `unknown_tlvs` arguments on struct fields. TLV fields are encoded as a
+//! `lightning_encoding::TlvStream` following all other fields.
 //!
 //! NB: TLVs work only with structures and not enums.
 //!
@@ -55,13 +56,15 @@
 //! Can be used with enum types only.
 //!
 //! Specifies which unsigned integer type must represent enum variants during
-//! the encoding. Possible values are `biging`, `u8`, `u16`, `u32` and `u64`.
+//! the encoding. Possible values are `u8`, `u16`, `u32` and `u64`; the value
+//! is encoded in big-endian byte order. Decoding of a value not matching any
+//! of the enum variants fails with `Error::EnumValueNotKnown`.
 //!
 //! NB: This argument is not equal to the rust `#[repr(...)]` attribute, which
 //! defines C FFI representation of the enum type. For their combined usage
 //! pls check examples below
 //!
-//! ### `bu_order`/`by_velue`
+//! ### `by_order`/`by_value`
 //!
 //! Can be used with enum types only, where they define which encoding strategy
 //! should be used for representation of enum variants:
@@ -87,7 +90,7 @@
 //! Allowed only for named and unnamed (tuple) structure fields and enum variant
 //! associated value fields.
 //!
-//! ### `tlv = <unsigned 16-bit int>`
+//! ### `tlv = <unsigned 64-bit int>`
 //!
 //! Sets the TLV type id for the field. The field type MUST be `Option` and
 //! it must implement `Default`.
@@ -96,7 +99,8 @@
 //!
 //! Specifies structure field which will be "capture all" for unknown odd TLV
 //! ids. The argument can be used only for a single field within a structure and
-//! the field type must be `BTreeMap<usize, Box<[u8]>]`.
+//! the field type must be either `lightning_encoding::TlvStream` or
+//! `BTreeMap<u64, Box<[u8]>>`.
 //!
 //! NB: if an unknown even TLV type id is met, error is raised and the value
 //! does not get into the field.
@@ -184,7 +188,7 @@
 //! };
 //! let ser = obj.lightning_serialize().unwrap();
 //!
-//! assert_eq!(ser, vec![0x00, 0x03, b'a', b'b', b'c']);
+//! assert_eq!(ser, vec![0x03, b'a', b'b', b'c']);
 //! let de = Skipping::lightning_deserialize(&ser).unwrap();
 //! assert_eq!(de.ephemeral, None);
 //! assert_eq!(obj.data, de.data);
@@ -198,6 +202,8 @@ extern crate syn;
 #[macro_use]
 extern crate amplify_syn;
 
+mod tlv;
+
 use encoding_derive_helpers::{decode_derive, encode_derive};
 use proc_macro::TokenStream;
 use syn::DeriveInput;
@@ -206,6 +212,11 @@ use syn::DeriveInput;
 #[proc_macro_derive(LightningEncode, attributes(lightning_encoding))]
 pub fn derive_lightning_encode(input: TokenStream) -> TokenStream {
     let derive_input = parse_macro_input!(input as DeriveInput);
+    if tlv::uses_tlv(&derive_input) {
+        return tlv::encode_derive(derive_input)
+            .unwrap_or_else(|e| e.to_compile_error())
+            .into();
+    }
     encode_derive(
         "lightning_encoding",
         ident!(lightning_encoding),
@@ -213,7 +224,7 @@ pub fn derive_lightning_encode(input: TokenStream) -> TokenStream {
         ident!(lightning_encode),
         ident!(lightning_serialize),
         derive_input,
-        true,
+        false,
     )
     .unwrap_or_else(|e| e.to_compile_error())
     .into()
@@ -223,6 +234,11 @@ pub fn derive_lightning_encode(input: TokenStream) -> TokenStream {
 #[proc_macro_derive(LightningDecode, attributes(lightning_encoding))]
 pub fn derive_lightning_decode(input: TokenStream) -> TokenStream {
     let derive_input = parse_macro_input!(input as DeriveInput);
+    if tlv::uses_tlv(&derive_input) {
+        return tlv::decode_derive(derive_input)
+            .unwrap_or_else(|e| e.to_compile_error())
+            .into();
+    }
     decode_derive(
         "lightning_encoding",
         ident!(lightning_encoding),
@@ -230,7 +246,7 @@ pub fn derive_lightning_decode(input: TokenStream) -> TokenStream {
         ident!(lightning_decode),
         ident!(lightning_deserialize),
         derive_input,
-        true,
+        false,
     )
     .unwrap_or_else(|e| e.to_compile_error())
     .into()
@@ -0,0 +1,299 @@
+// Derive macros for lightning network peer protocol encodings
+//
+// Written in 2020-2024 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License along with this
+// software. If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Derivation of structures with `use_tlv` argument. Such structures are
+//! encoded as a sequence of their non-TLV fields followed by
+//! `lightning_encoding::TlvStream` containing values of TLV fields and
+//! preserved unknown odd TLV records.
+
+use std::collections::{BTreeSet, HashMap};
+
+use amplify_syn::{
+    ArgValue, ArgValueReq, AttrReq, LiteralClass, ParametrizedAttr, ValueClass,
+};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    Data, DeriveInput, Error, Fields, Index, LitInt, Member, Path, Result, Type,
+};
+
+const ATTR_NAME: &str = "lightning_encoding";
+const CRATE: &str = "crate";
+const USE_TLV: &str = "use_tlv";
+const SKIP: &str = "skip";
+const TLV: &str = "tlv";
+const UNKNOWN_TLVS: &str = "unknown_tlvs";
+
+/// Role of a structure field in the encoding
+enum FieldKind {
+    /// Field encoded in order before the TLV stream
+    Fixed,
+    /// Field not encoded and initialized with `Default::default()`
+    Skipped,
+    /// Optional field encoded as a TLV record with the given type
+    Tlv(u64),
+    /// Field aggregating unknown odd TLV records
+    Unknown,
+}
+
+/// Structure with TLV extension, parsed from the derive input
+struct TlvStruct {
+    import: Path,
+    fields: Vec<(Member, FieldKind)>,
+}
+
+/// Detects whether derivation input requests TLV extension with
+/// `#[lightning_encoding(use_tlv)]` attribute
+pub(crate) fn uses_tlv(input: &DeriveInput) -> bool {
+    ParametrizedAttr::with(ATTR_NAME, &input.attrs)
+        .map(|attr| {
+            attr.args.contains_key(USE_TLV)
+                || attr.paths.iter().any(|path| path.is_ident(USE_TLV))
+        })
+        .unwrap_or_default()
+}
+
+/// Derives `LightningEncode` for a structure with TLV extension
+pub(crate) fn encode_derive(input: DeriveInput) -> Result<TokenStream2> {
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let ident_name = &input.ident;
+    let TlvStruct { import, fields } = TlvStruct::with(&input)?;
+
+    let mut stream = TokenStream2::new();
+    let mut tlvs = quote! { #import::TlvStream::new() };
+    let mut records = TokenStream2::new();
+    for (member, kind) in fields {
+        match kind {
+            FieldKind::Fixed => stream.extend(quote! {
+                len += #import::LightningEncode::lightning_encode(&self.#member, &mut e)?;
+            }),
+            FieldKind::Skipped => {}
+            FieldKind::Tlv(type_no) => records.extend(quote! {
+                if let Some(value) = &self.#member {
+                    tlvs.insert(#type_no, #import::LightningEncode::lightning_serialize(value)?);
+                }
+            }),
+            FieldKind::Unknown => {
+                tlvs = quote! { #import::TlvStream::from(self.#member.clone()) }
+            }
+        }
+    }
+    let mutability = if records.is_empty() {
+        quote! {}
+    } else {
+        quote! { mut }
+    };
+
+    Ok(quote! {
+        impl #impl_generics #import::LightningEncode for #ident_name #ty_generics #where_clause {
+            fn lightning_encode<E: ::std::io::Write>(&self, mut e: E) -> Result<usize, #import::Error> {
+                let mut len = 0;
+                #stream
+                let #mutability tlvs = #tlvs;
+                #records
+                len += #import::LightningEncode::lightning_encode(&tlvs, &mut e)?;
+                Ok(len)
+            }
+        }
+    })
+}
+
+/// Derives `LightningDecode` for a structure with TLV extension
+pub(crate) fn decode_derive(input: DeriveInput) -> Result<TokenStream2> {
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let ident_name = &input.ident;
+    let TlvStruct { import, fields } = TlvStruct::with(&input)?;
+
+    let known = fields.iter().filter_map(|(_, kind)| match kind {
+        FieldKind::Tlv(type_no) => Some(*type_no),
+        _ => None,
+    });
+    let unknown = if fields
+        .iter()
+        .any(|(_, kind)| matches!(kind, FieldKind::Unknown))
+    {
+        quote! { let unknown = }
+    } else {
+        quote! {}
+    };
+    // Non-TLV fields are read from the data before the TLV stream
+    let mut fixed = TokenStream2::new();
+    let mut stream = quote! {
+        let tlvs = <#import::TlvStream as #import::LightningDecode>::lightning_decode(&mut d)?;
+        #unknown tlvs.unknown_odd(&[#( #known ),*])?;
+    };
+    let mut constructor = TokenStream2::new();
+    for (member, kind) in fields {
+        let var = match &member {
+            Member::Named(ident) => format_ident!("field_{}", ident),
+            Member::Unnamed(index) => format_ident!("field_{}", index.index),
+        };
+        match kind {
+            FieldKind::Fixed => fixed.extend(quote! {
+                let #var = #import::LightningDecode::lightning_decode(&mut d)?;
+            }),
+            FieldKind::Skipped => stream.extend(quote! {
+                let #var = Default::default();
+            }),
+            FieldKind::Tlv(type_no) => stream.extend(quote! {
+                let #var = tlvs
+                    .get(#type_no)
+                    .map(|value| #import::LightningDecode::lightning_deserialize(value))
+                    .transpose()?;
+            }),
+            FieldKind::Unknown => stream.extend(quote! {
+                let #var = unknown.into();
+            }),
+        }
+        constructor.extend(quote! { #member: #var, });
+    }
+
+    Ok(quote! {
+        impl #impl_generics #import::LightningDecode for #ident_name #ty_generics #where_clause {
+            fn lightning_decode<D: ::std::io::Read>(mut d: D) -> Result<Self, #import::Error> {
+                #fixed
+                #stream
+                Ok(Self { #constructor })
+            }
+        }
+    })
+}
+
+impl TlvStruct {
+    fn with(input: &DeriveInput) -> Result<TlvStruct> {
+        let data = match &input.data {
+            Data::Struct(data) => data,
+            _ => {
+                return Err(Error::new_spanned(
+                    input,
+                    "TLV extensions are supported only for structures",
+                ))
+            }
+        };
+
+        let mut attr = ParametrizedAttr::with(ATTR_NAME, &input.attrs)?;
+        let mut req = HashMap::new();
+        req.insert(
+            CRATE,
+            ArgValueReq::with_default(ident!(lightning_encoding)),
+        );
+        req.insert(USE_TLV, ArgValueReq::with_default(true));
+        attr.check(AttrReq::with(req))?;
+        let import = attr
+            .args
+            .remove(CRATE)
+            .unwrap_or_else(|| ArgValue::from(ident!(lightning_encoding)))
+            .try_into()?;
+
+        let members: Vec<_> = match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .map(|field| {
+                    (field, Member::Named(field.ident.clone().expect("named")))
+                })
+                .collect(),
+            Fields::Unnamed(fields) => fields
+                .unnamed
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    (field, Member::Unnamed(Index::from(index)))
+                })
+                .collect(),
+            Fields::Unit => vec![],
+        };
+
+        let mut fields = Vec::with_capacity(members.len());
+        let mut tlv_types = BTreeSet::new();
+        let mut has_unknown = false;
+        for (field, member) in members {
+            let mut attr = ParametrizedAttr::with(ATTR_NAME, &field.attrs)?;
+            let mut req = HashMap::new();
+            req.insert(SKIP, ArgValueReq::Prohibited);
+            req.insert(
+                TLV,
+                ArgValueReq::Optional(ValueClass::Literal(LiteralClass::Int)),
+            );
+            req.insert(UNKNOWN_TLVS, ArgValueReq::Prohibited);
+            attr.check(AttrReq::with(req))?;
+
+            let kind = match (
+                attr.args.remove(SKIP),
+                attr.args.remove(TLV),
+                attr.args.remove(UNKNOWN_TLVS),
+            ) {
+                (None, None, None) => FieldKind::Fixed,
+                (Some(_), None, None) => FieldKind::Skipped,
+                (None, Some(type_no), None) => {
+                    let type_no: u64 = LitInt::try_from(type_no)
+                        .map_err(|_| {
+                            Error::new(
+                                field.span(),
+                                "`tlv` argument requires TLV type number",
+                            )
+                        })?
+                        .base10_parse()?;
+                    if !is_option(&field.ty) {
+                        return Err(Error::new(
+                            field.ty.span(),
+                            "TLV field must be of `Option` type",
+                        ));
+                    }
+                    if !tlv_types.insert(type_no) {
+                        return Err(Error::new(
+                            field.span(),
+                            format!("reused TLV type {}", type_no),
+                        ));
+                    }
+                    FieldKind::Tlv(type_no)
+                }
+                (None, None, Some(_)) => {
+                    if has_unknown {
+                        return Err(Error::new(
+                            field.span(),
+                            "unknown TLVs aggregator can be present only once",
+                        ));
+                    }
+                    has_unknown = true;
+                    FieldKind::Unknown
+                }
+                _ => {
+                    return Err(Error::new(
+                        field.span(),
+                        "`skip`, `tlv` and `unknown_tlvs` arguments are \
+                         mutually exclusive",
+                    ))
+                }
+            };
+            fields.push((member, kind));
+        }
+
+        Ok(TlvStruct { import, fields })
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "Option")
+            .unwrap_or_default(),
+        _ => false,
+    }
+}
@@ -0,0 +1,76 @@
+// Derive macros for lightning network peer protocol encodings
+//
+// Written in 2020-2024 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License along with this
+// software. If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+use lightning_encoding::{Error, LightningDecode, LightningEncode};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(LightningEncode, LightningDecode)]
+#[lightning_encoding(by_value, repr = u16)]
+#[repr(u16)]
+enum FailureFlag {
+    BadOnion = 0x8000,
+    Perm = 0x4000,
+    Node = 0x2000,
+    Update = 0x1000,
+
+    #[lightning_encoding(value = 0x0001)]
+    Custom = 0x0002,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(LightningEncode, LightningDecode)]
+#[lightning_encoding(by_order, repr = u16)]
+enum Ordered {
+    First,
+    Second,
+}
+
+#[test]
+fn enum_by_value() {
+    for (flag, ser) in [
+        (FailureFlag::BadOnion, [0x80, 0x00]),
+        (FailureFlag::Perm, [0x40, 0x00]),
+        (FailureFlag::Node, [0x20, 0x00]),
+        (FailureFlag::Update, [0x10, 0x00]),
+        (FailureFlag::Custom, [0x00, 0x01]),
+    ] {
+        assert_eq!(flag.lightning_serialize().unwrap(), ser);
+        assert_eq!(FailureFlag::lightning_deserialize(ser).unwrap(), flag);
+    }
+}
+
+#[test]
+fn enum_by_order() {
+    assert_eq!(Ordered::First.lightning_serialize().unwrap(), [0x00, 0x00]);
+    assert_eq!(Ordered::Second.lightning_serialize().unwrap(), [0x00, 0x01]);
+    assert_eq!(
+        Ordered::lightning_deserialize([0x00, 0x01]).unwrap(),
+        Ordered::Second
+    );
+}
+
+#[test]
+fn enum_unknown_value() {
+    assert_eq!(
+        FailureFlag::lightning_deserialize([0x00, 0x02]).unwrap_err(),
+        Error::EnumValueNotKnown("FailureFlag", 0x0002)
+    );
+    assert_eq!(
+        FailureFlag::lightning_deserialize([0x80, 0x01]).unwrap_err(),
+        Error::EnumValueNotKnown("FailureFlag", 0x8001)
+    );
+    assert_eq!(
+        Ordered::lightning_deserialize([0x01, 0x00]).unwrap_err(),
+        Error::EnumValueNotKnown("Ordered", 0x0100)
+    );
+}
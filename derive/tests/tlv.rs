@@ -0,0 +1,116 @@
+// Derive macros for lightning network peer protocol encodings
+//
+// Written in 2020-2024 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache 2.0 License along with this
+// software. If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+use std::collections::BTreeMap;
+
+use lightning_encoding::{LightningDecode, LightningEncode, TlvStream};
+
+#[derive(LightningEncode, LightningDecode)]
+#[lightning_encoding(use_tlv)]
+struct Mixed {
+    fixed_a: u16,
+    fixed_b: Vec<u8>,
+
+    #[lightning_encoding(tlv = 1)]
+    tlv_a: Option<u32>,
+
+    #[lightning_encoding(tlv = 2)]
+    tlv_b: Option<u8>,
+
+    #[lightning_encoding(unknown_tlvs)]
+    unknown_tlvs: TlvStream,
+}
+
+impl Mixed {
+    fn with(tlv_a: Option<u32>, tlv_b: Option<u8>) -> Mixed {
+        Mixed {
+            fixed_a: 0x1234,
+            fixed_b: vec![0xAA, 0xBB],
+            tlv_a,
+            tlv_b,
+            unknown_tlvs: TlvStream::new(),
+        }
+    }
+}
+
+#[derive(LightningEncode, LightningDecode)]
+#[lightning_encoding(use_tlv)]
+struct Tuple(
+    u8,
+    #[lightning_encoding(skip)] u8,
+    #[lightning_encoding(tlv = 0xFD)] Option<u8>,
+    #[lightning_encoding(unknown_tlvs)] BTreeMap<u64, Box<[u8]>>,
+);
+
+const FIXED: [u8; 5] = [0x12, 0x34, 0x02, 0xAA, 0xBB];
+
+#[test]
+fn tlv_none_skipped() {
+    let ser = Mixed::with(None, None).lightning_serialize().unwrap();
+    assert_eq!(ser, FIXED);
+
+    let de = Mixed::lightning_deserialize(&ser).unwrap();
+    assert_eq!(de.fixed_a, 0x1234);
+    assert_eq!(de.fixed_b, vec![0xAA, 0xBB]);
+    assert_eq!(de.tlv_a, None);
+    assert_eq!(de.tlv_b, None);
+}
+
+#[test]
+fn tlv_fields_ordered() {
+    let ser = Mixed::with(Some(0x01020304), Some(0xFF))
+        .lightning_serialize()
+        .unwrap();
+    let mut expected = FIXED.to_vec();
+    expected.extend([0x01, 0x04, 0x01, 0x02, 0x03, 0x04, 0x02, 0x01, 0xFF]);
+    assert_eq!(ser, expected);
+
+    let de = Mixed::lightning_deserialize(&ser).unwrap();
+    assert_eq!(de.tlv_a, Some(0x01020304));
+    assert_eq!(de.tlv_b, Some(0xFF));
+}
+
+#[test]
+fn tlv_unknown_odd_preserved() {
+    let mut data = FIXED.to_vec();
+    data.extend([0x02, 0x01, 0x07, 0x05, 0x02, 0xDE, 0xAD]);
+
+    let de = Mixed::lightning_deserialize(&data).unwrap();
+    assert_eq!(de.tlv_a, None);
+    assert_eq!(de.tlv_b, Some(0x07));
+    assert_eq!(de.lightning_serialize().unwrap(), data);
+}
+
+#[test]
+fn tlv_unknown_even_rejected() {
+    let mut data = FIXED.to_vec();
+    data.extend([0x06, 0x01, 0x00]);
+
+    assert!(Mixed::lightning_deserialize(&data).is_err());
+}
+
+#[test]
+fn tlv_tuple_struct() {
+    let mut unknown = BTreeMap::new();
+    unknown.insert(3u64, vec![0xBB].into_boxed_slice());
+    let ser = Tuple(0x01, 0x02, Some(0xAA), unknown)
+        .lightning_serialize()
+        .unwrap();
+    assert_eq!(ser, vec![
+        0x01, 0x03, 0x01, 0xBB, 0xFD, 0x00, 0xFD, 0x01, 0xAA
+    ]);
+
+    let de = Tuple::lightning_deserialize(&ser).unwrap();
+    assert_eq!((de.0, de.1, de.2), (0x01, 0x00, Some(0xAA)));
+    assert_eq!(&de.3[&3][..], &[0xBB]);
+}
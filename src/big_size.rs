@@ -267,4 +267,35 @@ mod test {
     fn test_eof_error_6() {
         BigSize::lightning_deserialize(&[0xff]).unwrap();
     }
+
+    #[test]
+    fn test_canonical_boundaries() {
+        let boundaries: [(u64, &[u8], &[u8]); 3] = [
+            (0xfc, &[0xfc], &[0xfd, 0x00, 0xfc]),
+            (0xffff, &[0xfd, 0xff, 0xff], &[0xfe, 0x00, 0x00, 0xff, 0xff]),
+            (0xffffffff, &[0xfe, 0xff, 0xff, 0xff, 0xff], &[
+                0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+            ]),
+        ];
+        for (value, canonical, non_canonical) in boundaries {
+            // Largest value fitting the shorter encoding
+            test_runner(value, canonical);
+            assert_eq!(BigSize(value).len(), canonical.len());
+            assert_eq!(
+                BigSize::lightning_deserialize(non_canonical),
+                Err(Error::BigSizeNotCanonical)
+            );
+            assert_eq!(
+                usize::lightning_deserialize(non_canonical),
+                Err(Error::BigSizeNotCanonical)
+            );
+
+            // Smallest value requiring the longer encoding
+            let next = BigSize(value + 1);
+            let encoded = next.lightning_serialize().unwrap();
+            assert_eq!(encoded.len(), non_canonical.len());
+            assert_eq!(next.len(), non_canonical.len());
+            assert_eq!(BigSize::lightning_deserialize(&encoded), Ok(next));
+        }
+    }
 }
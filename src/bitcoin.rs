@@ -13,9 +13,15 @@
 
 use std::io::{Read, Write};
 
-use bitcoin::{consensus, hashes, secp256k1, PubkeyHash, Script};
+use amplify::Wrapper;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::{self, Decodable, Encodable};
+use bitcoin::{
+    hashes, secp256k1, Amount, BlockHash, Network, PubkeyHash, Script,
+    Transaction, TxIn, TxOut,
+};
 use bitcoin_scripts::{hlc, PubkeyScript};
-use lnpbp_chain::AssetId;
+use lnpbp_chain::{AssetId, Chain};
 
 use super::{strategies, Strategy};
 use crate::{Error, LightningDecode, LightningEncode};
@@ -56,6 +62,10 @@ impl Strategy for bitcoin::Txid {
     type Strategy = strategies::AsBitcoinHash;
 }
 
+impl Strategy for BlockHash {
+    type Strategy = strategies::AsBitcoinHash;
+}
+
 impl Strategy for bitcoin::OutPoint {
     type Strategy = strategies::AsStrict;
 }
@@ -76,8 +86,28 @@ impl Strategy for secp256k1::SecretKey {
     type Strategy = strategies::AsStrict;
 }
 
-impl Strategy for secp256k1::ecdsa::Signature {
-    type Strategy = strategies::AsStrict;
+/// Signatures are encoded in 64-byte compact form
+impl LightningEncode for secp256k1::ecdsa::Signature {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        self.serialize_compact().lightning_encode(e)
+    }
+}
+
+/// Only signatures in lower-S form are valid in lightning protocol; any other
+/// signature fails decoding
+impl LightningDecode for secp256k1::ecdsa::Signature {
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        let data = <[u8; 64]>::lightning_decode(d)?;
+        let sig = secp256k1::ecdsa::Signature::from_compact(&data)
+            .map_err(|err| Error::DataIntegrityError(err.to_string()))?;
+        let mut normalized = sig;
+        normalized.normalize_s();
+        if normalized != sig {
+            return Err(Error::SignatureHighS);
+        }
+        Ok(sig)
+    }
 }
 
 impl Strategy for hlc::HashLock {
@@ -88,21 +118,235 @@ impl Strategy for hlc::HashPreimage {
     type Strategy = strategies::AsStrict;
 }
 
-impl LightningEncode for Script {
+/// Amounts are encoded as a number of satoshis in big-endian `u64`
+impl LightningEncode for Amount {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        self.to_sat().lightning_encode(e)
+    }
+}
+
+impl LightningDecode for Amount {
+    #[inline]
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        u64::lightning_decode(d).map(Amount::from_sat)
+    }
+}
+
+/// Amount in millisatoshis, as used for channel balances and HTLC values,
+/// encoded as big-endian `u64`
+#[derive(
+    Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default
+)]
+pub struct MilliSatoshi(u64);
+
+impl From<u64> for MilliSatoshi {
+    #[inline]
+    fn from(msat: u64) -> Self {
+        MilliSatoshi(msat)
+    }
+}
+
+impl MilliSatoshi {
+    /// Converts bitcoin amount into millisatoshis, returning `None` on
+    /// overflow
+    #[inline]
+    pub fn from_amount(amount: Amount) -> Option<Self> {
+        amount.to_sat().checked_mul(1000).map(MilliSatoshi)
+    }
+
+    /// Converts into bitcoin amount, rounding down to the whole satoshis
+    #[inline]
+    pub fn to_amount_floor(self) -> Amount {
+        Amount::from_sat(self.0 / 1000)
+    }
+}
+
+impl LightningEncode for MilliSatoshi {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        self.0.lightning_encode(e)
+    }
+}
+
+impl LightningDecode for MilliSatoshi {
+    #[inline]
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        u64::lightning_decode(d).map(MilliSatoshi)
+    }
+}
+
+/// Networks are encoded as the hash of their genesis block, in the same way
+/// as `chain_hash` fields of lightning messages
+impl LightningEncode for Network {
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        genesis_block(*self).block_hash().lightning_encode(e)
+    }
+}
+
+impl LightningDecode for Network {
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        let genesis_hash = BlockHash::lightning_decode(d)?;
+        [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .into_iter()
+        .find(|network| genesis_block(*network).block_hash() == genesis_hash)
+        .ok_or(Error::UnknownChain(genesis_hash))
+    }
+}
+
+/// Chains are encoded as the hash of their genesis block, like [`Network`].
+///
+/// Decoding resolves only chains known by [`Chain::from_genesis_hash`]; other
+/// hashes (including custom signets and regtests with a modified genesis)
+/// fail with [`Error::UnknownChain`], since [`Chain::Other`] requires full
+/// chain parameters which can't be recovered from the genesis hash alone.
+impl LightningEncode for Chain {
     #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        self.as_genesis_hash().lightning_encode(e)
+    }
+}
+
+impl LightningDecode for Chain {
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        let genesis_hash = BlockHash::lightning_decode(d)?;
+        Chain::from_genesis_hash(&genesis_hash)
+            .ok_or(Error::UnknownChain(genesis_hash))
+    }
+}
+
+/// Scripts are prefixed with their length as `u16`, the same way as
+/// `scriptpubkey` fields are encoded in lightning messages. For scripts taking
+/// all of the remaining data use [`RawScript`].
+impl LightningEncode for Script {
     fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        let len = u16::try_from(self.len())
+            .map_err(|_| Error::TooLargeData(self.len()))?;
+        len.lightning_encode(&mut e)?;
         e.write_all(self.as_bytes())?;
-        Ok(self.len())
+        Ok(self.len() + 2)
     }
 }
 
 impl LightningDecode for Script {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        let len = u16::lightning_decode(&mut d)?;
+        let mut buf = vec![0u8; len as usize];
+        d.read_exact(&mut buf)?;
+        Ok(Script::from(buf))
+    }
+}
+
+/// Script which is not prefixed with its length and which takes all of the
+/// remaining data on decoding.
+///
+/// Must be used only for data which always come last, like values of TLV
+/// records (for instance `upfront_shutdown_script`); scripts in message fields
+/// are length-prefixed and must use [`Script`] encoding.
+#[derive(Wrapper, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct RawScript(Script);
+
+impl From<Script> for RawScript {
+    #[inline]
+    fn from(script: Script) -> Self {
+        RawScript(script)
+    }
+}
+
+impl LightningEncode for RawScript {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        e.write_all(self.0.as_bytes())?;
+        Ok(self.0.len())
+    }
+}
+
+impl LightningDecode for RawScript {
     fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
         let mut buf = vec![];
         d.read_to_end(&mut buf)?;
-        let bytes = consensus::serialize(&buf);
-        consensus::deserialize(&bytes)
-            .map_err(|err| Error::DataIntegrityError(err.to_string()))
+        Ok(RawScript(Script::from(buf)))
+    }
+}
+
+/// Encodes data with bitcoin consensus serialization prefixed with its length
+/// as `u16`
+fn consensus_encode_prefixed<T: Encodable>(
+    data: &T,
+    mut e: impl Write,
+) -> Result<usize, Error> {
+    let ser = consensus::serialize(data);
+    let len =
+        u16::try_from(ser.len()).map_err(|_| Error::TooLargeData(ser.len()))?;
+    len.lightning_encode(&mut e)?;
+    e.write_all(&ser)?;
+    Ok(ser.len() + 2)
+}
+
+/// Decodes `u16` length-prefixed data in bitcoin consensus serialization,
+/// requiring all of the prefixed data to be consumed
+fn consensus_decode_prefixed<T: Decodable>(
+    mut d: impl Read,
+) -> Result<T, Error> {
+    let len = u16::lightning_decode(&mut d)?;
+    let mut buf = vec![0u8; len as usize];
+    d.read_exact(&mut buf)?;
+    consensus::deserialize(&buf)
+        .map_err(|err| Error::DataIntegrityError(err.to_string()))
+}
+
+/// Transaction outputs are encoded with consensus serialization prefixed
+/// with its length as `u16`
+impl LightningEncode for TxOut {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        consensus_encode_prefixed(self, e)
+    }
+}
+
+impl LightningDecode for TxOut {
+    #[inline]
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        consensus_decode_prefixed(d)
+    }
+}
+
+/// Transaction inputs are encoded with consensus serialization prefixed
+/// with its length as `u16`. Consensus serialization of an input does not
+/// include its witness, so the witness is always empty after decoding.
+impl LightningEncode for TxIn {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        consensus_encode_prefixed(self, e)
+    }
+}
+
+impl LightningDecode for TxIn {
+    #[inline]
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        consensus_decode_prefixed(d)
+    }
+}
+
+/// Transactions are encoded with consensus serialization (including
+/// witnesses, if present) prefixed with its length as `u16`, like `prevtx`
+/// field of `tx_add_input` message
+impl LightningEncode for Transaction {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        consensus_encode_prefixed(self, e)
+    }
+}
+
+impl LightningDecode for Transaction {
+    #[inline]
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        consensus_decode_prefixed(d)
     }
 }
 
@@ -114,12 +358,17 @@ impl Strategy for PubkeyHash {
     type Strategy = strategies::AsStrict;
 }
 
+/// Asset id of a chain native asset is the chain genesis block hash, which
+/// allows using [`AssetId`] for `chain_hash` fields of lightning messages; it
+/// is encoded in exactly the same way as [`BlockHash`].
 impl Strategy for AssetId {
     type Strategy = strategies::AsBitcoinHash;
 }
 
 #[cfg(test)]
 mod test {
+    use bitcoin::hashes::Hash;
+
     use super::*;
 
     #[test]
@@ -133,4 +382,219 @@ mod test {
         let script = PubkeyScript::lightning_deserialize(&msg_recv).unwrap();
         assert_eq!(script.lightning_serialize().unwrap(), msg_recv);
     }
+
+    #[test]
+    fn amount() {
+        let amount = Amount::from_sat(0x0102030405060708);
+        let ser = amount.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        assert_eq!(Amount::lightning_deserialize(&ser).unwrap(), amount);
+
+        for amount in [Amount::ZERO, Amount::ONE_BTC, Amount::MAX_MONEY] {
+            let ser = amount.lightning_serialize().unwrap();
+            assert_eq!(ser, amount.to_sat().lightning_serialize().unwrap());
+            assert_eq!(Amount::lightning_deserialize(&ser).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn msat() {
+        let msat = MilliSatoshi::from(1_234_567);
+        let ser = msat.lightning_serialize().unwrap();
+        assert_eq!(ser, 1_234_567u64.lightning_serialize().unwrap());
+        assert_eq!(MilliSatoshi::lightning_deserialize(&ser).unwrap(), msat);
+
+        assert_eq!(msat.to_amount_floor(), Amount::from_sat(1_234));
+        assert_eq!(MilliSatoshi::from(999).to_amount_floor(), Amount::ZERO);
+        assert_eq!(
+            MilliSatoshi::from(u64::MAX).to_amount_floor(),
+            Amount::from_sat(u64::MAX / 1000)
+        );
+
+        assert_eq!(
+            MilliSatoshi::from_amount(Amount::from_sat(1_234)),
+            Some(MilliSatoshi::from(1_234_000))
+        );
+        assert_eq!(
+            MilliSatoshi::from_amount(Amount::MAX_MONEY)
+                .map(MilliSatoshi::to_amount_floor),
+            Some(Amount::MAX_MONEY)
+        );
+        assert_eq!(
+            MilliSatoshi::from_amount(Amount::from_sat(u64::MAX / 1000 + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn network() {
+        let ser = Network::Bitcoin.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![
+            0x6f, 0xe2, 0x8c, 0x0a, 0xb6, 0xf1, 0xb3, 0x72, 0xc1, 0xa6, 0xa2,
+            0x46, 0xae, 0x63, 0xf7, 0x4f, 0x93, 0x1e, 0x83, 0x65, 0xe1, 0x5a,
+            0x08, 0x9c, 0x68, 0xd6, 0x19, 0x00, 0x00, 0x00, 0x00, 0x00
+        ]);
+
+        for network in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ] {
+            let ser = network.lightning_serialize().unwrap();
+            assert_eq!(ser.len(), 32);
+            assert_eq!(Network::lightning_deserialize(&ser).unwrap(), network);
+        }
+
+        assert_eq!(
+            Network::lightning_deserialize(&[0xA5; 32]).unwrap_err(),
+            Error::UnknownChain(BlockHash::from_inner([0xA5; 32]))
+        );
+    }
+
+    #[test]
+    fn chain() {
+        for network in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ] {
+            let chain = Chain::from(network);
+            let ser = chain.lightning_serialize().unwrap();
+            assert_eq!(ser, network.lightning_serialize().unwrap());
+            assert_eq!(Chain::lightning_deserialize(&ser).unwrap(), chain);
+        }
+
+        let ser = Chain::LiquidV1.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), 32);
+        assert_eq!(
+            Chain::lightning_deserialize(&ser).unwrap(),
+            Chain::LiquidV1
+        );
+
+        let custom = BlockHash::from_inner([0xA5; 32]);
+        let ser = Chain::SignetCustom(custom).lightning_serialize().unwrap();
+        assert_eq!(ser, [0xA5; 32]);
+        assert_eq!(
+            Chain::lightning_deserialize(&ser).unwrap_err(),
+            Error::UnknownChain(custom)
+        );
+    }
+
+    #[test]
+    fn signature_low_s() {
+        let mut data = [0x01u8; 64];
+        data[32..].copy_from_slice(&[0u8; 32]);
+        data[63] = 0x01;
+
+        let sig =
+            secp256k1::ecdsa::Signature::lightning_deserialize(&data).unwrap();
+        assert_eq!(sig.lightning_serialize().unwrap(), data.to_vec());
+
+        // s = n - 1, where n is the order of secp256k1 curve
+        data[32..].copy_from_slice(&[
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFE, 0xBA, 0xAE, 0xDC, 0xE6, 0xAF, 0x48,
+            0xA0, 0x3B, 0xBF, 0xD2, 0x5E, 0x8C, 0xD0, 0x36, 0x41, 0x40,
+        ]);
+        assert_eq!(
+            secp256k1::ecdsa::Signature::lightning_deserialize(&data)
+                .unwrap_err(),
+            Error::SignatureHighS
+        );
+        let mut high_s =
+            secp256k1::ecdsa::Signature::from_compact(&data).unwrap();
+        high_s.normalize_s();
+        assert_eq!(high_s, sig);
+    }
+
+    #[test]
+    fn script_length_prefixed() {
+        let msg_recv = [
+            0u8, 22, 0, 20, 42, 238, 172, 27, 222, 161, 61, 181, 251, 208, 97,
+            79, 71, 255, 98, 8, 213, 205, 114, 94,
+        ];
+
+        let script = Script::lightning_deserialize(&msg_recv).unwrap();
+        assert!(script.is_v0_p2wpkh());
+        assert_eq!(script.as_bytes(), &msg_recv[2..]);
+        assert_eq!(script.lightning_serialize().unwrap(), msg_recv);
+
+        assert!(Script::lightning_deserialize(&msg_recv[..23]).is_err());
+        assert_eq!(
+            Script::lightning_deserialize(&[0x00, 0x00]).unwrap(),
+            Script::new()
+        );
+    }
+
+    #[test]
+    fn script_raw() {
+        let data = [
+            0u8, 20, 42, 238, 172, 27, 222, 161, 61, 181, 251, 208, 97, 79, 71,
+            255, 98, 8, 213, 205, 114, 94,
+        ];
+
+        let script = RawScript::lightning_deserialize(&data).unwrap();
+        assert!(script.as_inner().is_v0_p2wpkh());
+        assert_eq!(script.lightning_serialize().unwrap(), data);
+        assert_eq!(
+            RawScript::lightning_deserialize(&[]).unwrap(),
+            RawScript::default()
+        );
+    }
+
+    #[test]
+    fn block_hash() {
+        let hash = genesis_block(Network::Testnet).block_hash();
+        let ser = hash.lightning_serialize().unwrap();
+        assert_eq!(ser, hash.into_inner().to_vec());
+        assert_eq!(ser, Network::Testnet.lightning_serialize().unwrap());
+        assert_eq!(BlockHash::lightning_deserialize(&ser).unwrap(), hash);
+
+        let asset_id = AssetId::from_inner(hash.into_inner());
+        assert_eq!(asset_id.lightning_serialize().unwrap(), ser);
+    }
+
+    #[test]
+    fn tx_out() {
+        let coinbase = genesis_block(Network::Bitcoin).txdata.remove(0);
+        let tx_out = coinbase.output[0].clone();
+        let ser = tx_out.lightning_serialize().unwrap();
+        let consensus = consensus::serialize(&tx_out);
+        assert_eq!(ser[..2], (consensus.len() as u16).to_be_bytes());
+        assert_eq!(ser[2..], consensus);
+        assert_eq!(TxOut::lightning_deserialize(&ser).unwrap(), tx_out);
+    }
+
+    #[test]
+    fn tx_in_witness_excluded() {
+        let tx_in = TxIn {
+            witness: bitcoin::Witness::from_vec(vec![vec![0xAA, 0xBB]]),
+            ..TxIn::default()
+        };
+        let ser = tx_in.lightning_serialize().unwrap();
+        assert_eq!(ser[2..], consensus::serialize(&tx_in));
+
+        let decoded = TxIn::lightning_deserialize(&ser).unwrap();
+        assert!(decoded.witness.is_empty());
+        assert_eq!(decoded, TxIn {
+            witness: bitcoin::Witness::default(),
+            ..tx_in
+        });
+    }
+
+    #[test]
+    fn transaction() {
+        let coinbase = genesis_block(Network::Bitcoin).txdata.remove(0);
+        let ser = coinbase.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), consensus::serialize(&coinbase).len() + 2);
+        assert_eq!(Transaction::lightning_deserialize(&ser).unwrap(), coinbase);
+
+        // Prefixed length must cover the whole transaction
+        let mut truncated = ser.clone();
+        truncated[1] -= 1;
+        truncated.pop();
+        assert!(Transaction::lightning_deserialize(&truncated).is_err());
+    }
 }
@@ -11,10 +11,11 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use std::io;
+use std::io::{self, Read};
 use std::ops::Deref;
 
 use super::{Error, LightningDecode, LightningEncode};
+use crate::collections::prealloc_capacity;
 
 impl LightningEncode for &[u8] {
     fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
@@ -51,27 +52,118 @@ impl LightningEncode for Box<[u8]> {
 impl LightningDecode for Box<[u8]> {
     fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
         let len = usize::lightning_decode(&mut d)?;
-        let mut ret = vec![0u8; len];
-        d.read_exact(&mut ret)?;
+        // We do not trust the length prefix and do not pre-allocate the
+        // buffer; instead it grows as the data are read
+        let mut ret = Vec::with_capacity(prealloc_capacity::<u8>(len));
+        d.take(len as u64).read_to_end(&mut ret)?;
+        if ret.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
         Ok(ret.into_boxed_slice())
     }
 }
 
+/// Strings are prefixed with their length in bytes as `u16`; thus a string
+/// can't be larger than 65535 bytes.
 impl LightningEncode for &str {
-    fn lightning_encode<E: io::Write>(&self, e: E) -> Result<usize, Error> {
-        self.as_bytes().lightning_encode(e)
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let len = u16::try_from(self.len())
+            .map_err(|_| Error::TooLargeData(self.len()))?;
+        len.lightning_encode(&mut e)?;
+        e.write_all(self.as_bytes())?;
+        Ok(self.len() + 2)
     }
 }
 
 impl LightningEncode for String {
     fn lightning_encode<E: io::Write>(&self, e: E) -> Result<usize, Error> {
-        self.as_bytes().lightning_encode(e)
+        self.as_str().lightning_encode(e)
     }
 }
 
 impl LightningDecode for String {
-    fn lightning_decode<D: io::Read>(d: D) -> Result<Self, Error> {
-        Ok(String::from_utf8_lossy(&Vec::<u8>::lightning_decode(d)?)
-            .to_string())
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let len = u16::lightning_decode(&mut d)?;
+        let mut buf = vec![0u8; len as usize];
+        d.read_exact(&mut buf)?;
+        String::from_utf8(buf)
+            .map_err(|err| Error::InvalidUtf8(err.utf8_error().valid_up_to()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fixed_arrays() {
+        assert_eq!([0u8; 0].lightning_serialize().unwrap(), Vec::<u8>::new());
+        assert_eq!(<[u8; 0]>::lightning_deserialize(&[]).unwrap(), [0u8; 0]);
+
+        let secret = [0xAFu8; 32];
+        let ser = secret.lightning_serialize().unwrap();
+        assert_eq!(ser, secret.to_vec());
+        assert_eq!(<[u8; 32]>::lightning_deserialize(&ser).unwrap(), secret);
+
+        let mut hop = [0u8; 65];
+        hop.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        let ser = hop.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), 65);
+        assert_eq!(<[u8; 65]>::lightning_deserialize(&ser).unwrap(), hop);
+    }
+
+    #[test]
+    fn fixed_array_short_read() {
+        let err = <[u8; 32]>::lightning_deserialize(&[0xAFu8; 31]).unwrap_err();
+        assert_eq!(err, Error::from(io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn boxed_slice_oversized_len() {
+        let err = Box::<[u8]>::lightning_deserialize(&[0xfd, 0xff, 0xff, 0x00])
+            .unwrap_err();
+        assert_eq!(err, Error::from(io::ErrorKind::UnexpectedEof));
+        assert!(Box::<[u8]>::lightning_deserialize(&[0xff; 9]).is_err());
+
+        let data = Box::<[u8]>::from(&b"abc"[..]);
+        let ser = data.lightning_serialize().unwrap();
+        assert_eq!(Box::<[u8]>::lightning_deserialize(&ser).unwrap(), data);
+    }
+
+    #[test]
+    fn string() {
+        let empty = String::new();
+        let ser = empty.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x00, 0x00]);
+        assert_eq!(String::lightning_deserialize(&ser).unwrap(), empty);
+
+        let alias = s!("⚡ satoshi ₿");
+        let ser = alias.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), alias.len() + 2);
+        assert_eq!(&ser[..2], &(alias.len() as u16).to_be_bytes());
+        assert_eq!(&ser[2..], alias.as_bytes());
+        assert_eq!("⚡ satoshi ₿".lightning_serialize().unwrap(), ser);
+        assert_eq!(String::lightning_deserialize(&ser).unwrap(), alias);
+    }
+
+    #[test]
+    fn string_bounded_read() {
+        let data = [0x00, 0x02, b'o', b'k', 0xFF];
+        let mut cursor = io::Cursor::new(&data[..]);
+        assert_eq!(String::lightning_decode(&mut cursor).unwrap(), "ok");
+        assert_eq!(cursor.position(), 4);
+    }
+
+    #[test]
+    fn string_invalid_utf8() {
+        let err = String::lightning_deserialize(&[0x00, 0x02, 0xC3, 0x28])
+            .unwrap_err();
+        assert_eq!(err, Error::InvalidUtf8(0));
+
+        let long = "x".repeat(0x10000);
+        assert_eq!(
+            long.lightning_serialize().unwrap_err(),
+            Error::TooLargeData(0x10000)
+        );
     }
 }
@@ -0,0 +1,176 @@
+// Network encoding for lightning network peer protocol data types
+// Written in 2020-2024 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::io;
+
+use crate::{Error, LightningDecode, LightningEncode};
+
+/// Payload followed by big-endian CRC32 (IEEE 802.3) checksum of its encoding,
+/// which is verified on decoding.
+///
+/// This is an opt-in integrity check for experimental messages and custom
+/// transports and is not a part of BOLT message framing. The payload must be
+/// self-delimiting, i.e. its decoding must not read all of the remaining data
+/// (like [`crate::RawScript`] or [`crate::TlvStream`] do).
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ChecksummedPayload<T>(T);
+
+impl<T> ChecksummedPayload<T> {
+    /// Returns reference to the wrapped payload
+    #[inline]
+    pub fn as_inner(&self) -> &T {
+        &self.0
+    }
+
+    /// Returns the wrapped payload, consuming the wrapper
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for ChecksummedPayload<T> {
+    #[inline]
+    fn from(payload: T) -> Self {
+        ChecksummedPayload(payload)
+    }
+}
+
+impl<T> LightningEncode for ChecksummedPayload<T>
+where
+    T: LightningEncode,
+{
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let data = self.0.lightning_serialize()?;
+        let mut crc = Crc32::new();
+        crc.update(&data);
+        e.write_all(&data)?;
+        Ok(data.len() + crc.finalize().lightning_encode(e)?)
+    }
+}
+
+impl<T> LightningDecode for ChecksummedPayload<T>
+where
+    T: LightningDecode,
+{
+    fn lightning_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+        let mut reader = CrcReader {
+            inner: d,
+            crc: Crc32::new(),
+        };
+        let payload = T::lightning_decode(&mut reader)?;
+        let checksum = u32::lightning_decode(&mut reader.inner)?;
+        let expected = reader.crc.finalize();
+        if checksum != expected {
+            return Err(Error::DataIntegrityError(format!(
+                "payload checksum mismatch: expected {:#010x}, found {:#010x}",
+                expected, checksum
+            )));
+        }
+        Ok(ChecksummedPayload(payload))
+    }
+}
+
+/// Bitwise CRC32 computation with the reflected IEEE 802.3 polynomial
+struct Crc32(u32);
+
+impl Crc32 {
+    const POLY: u32 = 0xEDB8_8320;
+
+    fn new() -> Self {
+        Crc32(!0)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.0 ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (Self::POLY & mask);
+            }
+        }
+    }
+
+    fn finalize(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// Reader computing CRC32 of all data read from the underlying reader
+struct CrcReader<R: io::Read> {
+    inner: R,
+    crc: Crc32,
+}
+
+impl<R: io::Read> io::Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.crc.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crc32() {
+        let mut crc = Crc32::new();
+        assert_eq!(crc.finalize(), 0);
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finalize(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let payload = ChecksummedPayload::from((0x1234u16, vec![0xAAu8, 0xBB]));
+        let ser = payload.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), 2 + 1 + 2 + 4);
+        assert_eq!(ser[..5], [0x12, 0x34, 0x02, 0xAA, 0xBB]);
+
+        let mut crc = Crc32::new();
+        crc.update(&ser[..5]);
+        assert_eq!(ser[5..], crc.finalize().to_be_bytes());
+
+        assert_eq!(
+            ChecksummedPayload::<(u16, Vec<u8>)>::lightning_deserialize(&ser)
+                .unwrap(),
+            payload
+        );
+        assert_eq!(payload.into_inner(), (0x1234u16, vec![0xAAu8, 0xBB]));
+    }
+
+    #[test]
+    fn corrupted() {
+        let payload = ChecksummedPayload::from(s!("lightning"));
+        let ser = payload.lightning_serialize().unwrap();
+        for pos in 0..ser.len() {
+            let mut corrupted = ser.clone();
+            corrupted[pos] ^= 0x01;
+            assert!(ChecksummedPayload::<String>::lightning_deserialize(
+                &corrupted
+            )
+            .is_err());
+        }
+
+        let mut corrupted = ser;
+        corrupted[4] = b'L';
+        assert!(matches!(
+            ChecksummedPayload::<String>::lightning_deserialize(&corrupted)
+                .unwrap_err(),
+            Error::DataIntegrityError(_)
+        ));
+    }
+}
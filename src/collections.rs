@@ -14,15 +14,36 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io;
 
-use super::{Error, LightningDecode, LightningEncode};
+use amplify::Wrapper;
 
+use super::{
+    Error, LightningDecode, LightningEncode, LIGHTNING_P2P_MAX_MESSAGE_SIZE,
+};
+
+/// Returns number of items of type `T` which can be pre-allocated for a
+/// collection with `count` elements read from an untrusted source, such that
+/// the allocation does not exceed [`LIGHTNING_P2P_MAX_MESSAGE_SIZE`].
+pub(crate) fn prealloc_capacity<T>(count: usize) -> usize {
+    let item_size = std::mem::size_of::<T>().max(1);
+    count.min(LIGHTNING_P2P_MAX_MESSAGE_SIZE / item_size)
+}
+
+/// Optional values are encoded with a single-byte presence flag (`0` for
+/// `None` and `1` for `Some`) followed by the value itself, so they can be
+/// placed at any position within a data structure.
+///
+/// NB: Optional trailing fields defined by BOLTs as TLV records must use the
+/// `tlv` argument of the derive macros instead.
 impl<T> LightningEncode for Option<T>
 where
     T: LightningEncode,
 {
     fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
         Ok(1 + match self {
-            None => e.write(&[0u8])?,
+            None => {
+                e.write_all(&[0u8])?;
+                0
+            }
             Some(val) => {
                 e.write_all(&[1u8])?;
                 val.lightning_encode(&mut e)?
@@ -31,6 +52,8 @@ where
     }
 }
 
+/// Decodes optional value encoded with a single-byte presence flag, failing
+/// on any flag value other than `0` or `1`.
 impl<T> LightningDecode for Option<T>
 where
     T: LightningDecode,
@@ -41,7 +64,7 @@ where
         match flag[0] {
             0 => Ok(None),
             1 => Ok(Some(T::lightning_decode(&mut d)?)),
-            _ => Err(Error::DataIntegrityError(s!("wrong optional encoding"))),
+            flag => Err(Error::InvalidOptionFlag(flag)),
         }
     }
 }
@@ -63,7 +86,7 @@ where
 {
     fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
         let count = usize::lightning_decode(&mut d)?;
-        let mut vec = Vec::with_capacity(count);
+        let mut vec = Vec::with_capacity(prealloc_capacity::<T>(count));
         for _ in 0..count {
             vec.push(T::lightning_decode(&mut d)?)
         }
@@ -71,26 +94,36 @@ where
     }
 }
 
+/// Set elements are sorted before encoding, so sets with the same elements
+/// always have the same encoding independently from their iteration order.
 impl<T> LightningEncode for HashSet<T>
 where
-    T: LightningEncode,
+    T: LightningEncode + Ord,
 {
     fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
         let len = self.len().lightning_encode(&mut e)?;
-        self.iter()
+        let mut items = self.iter().collect::<Vec<_>>();
+        items.sort();
+        items
+            .into_iter()
             .try_fold(len, |len, item| Ok(len + item.lightning_encode(&mut e)?))
     }
 }
 
+/// Decoding fails on repeated set elements, but accepts elements in any
+/// order, since sets encoded by earlier versions were written in their
+/// iteration order.
 impl<T> LightningDecode for HashSet<T>
 where
     T: LightningDecode + Eq + std::hash::Hash,
 {
     fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
         let count = usize::lightning_decode(&mut d)?;
-        let mut set = HashSet::with_capacity(count);
+        let mut set = HashSet::with_capacity(prealloc_capacity::<T>(count));
         for _ in 0..count {
-            set.insert(T::lightning_decode(&mut d)?);
+            if !set.insert(T::lightning_decode(&mut d)?) {
+                return Err(Error::RepeatedKey);
+            }
         }
         Ok(set)
     }
@@ -118,7 +151,8 @@ where
 {
     fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
         let count = usize::lightning_decode(&mut d)?;
-        let mut set = HashMap::with_capacity(count);
+        let mut set =
+            HashMap::with_capacity(prealloc_capacity::<(K, V)>(count));
         for _ in 0..count {
             set.insert(
                 K::lightning_decode(&mut d)?,
@@ -169,6 +203,9 @@ where
     }
 }
 
+/// Map entries are encoded in ascending key order; on decode any entry with a
+/// key which is repeated or follows a larger key results in an error, so each
+/// map has a single canonical encoding.
 impl<K, V> LightningDecode for BTreeMap<K, V>
 where
     K: LightningDecode + Ord,
@@ -176,14 +213,19 @@ where
 {
     fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
         let count = usize::lightning_decode(&mut d)?;
-        let mut set = BTreeMap::new();
+        let mut map = BTreeMap::new();
         for _ in 0..count {
-            set.insert(
-                K::lightning_decode(&mut d)?,
-                V::lightning_decode(&mut d)?,
-            );
+            let key = K::lightning_decode(&mut d)?;
+            if let Some((last, _)) = map.iter().next_back() {
+                if &key == last {
+                    return Err(Error::RepeatedKey);
+                } else if &key < last {
+                    return Err(Error::UnorderedKeys);
+                }
+            }
+            map.insert(key, V::lightning_decode(&mut d)?);
         }
-        Ok(set)
+        Ok(map)
     }
 }
 
@@ -213,3 +255,344 @@ where
         Ok((a, b))
     }
 }
+
+/// Sequence of items prefixed with `u16` item count (unlike [`Vec`], which
+/// uses `BigSize` count prefix), as BOLTs encode arrays of fixed-size items
+/// like `htlc_signatures` in `commitment_signed` message.
+#[derive(Wrapper, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct U16Vec<T>(Vec<T>)
+where
+    T: Clone;
+
+impl<T> From<Vec<T>> for U16Vec<T>
+where
+    T: Clone,
+{
+    #[inline]
+    fn from(vec: Vec<T>) -> Self {
+        U16Vec(vec)
+    }
+}
+
+impl<T> LightningEncode for U16Vec<T>
+where
+    T: LightningEncode + Clone,
+{
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let count = u16::try_from(self.0.len())
+            .map_err(|_| Error::TooLargeData(self.0.len()))?;
+        let len = count.lightning_encode(&mut e)?;
+        self.0
+            .iter()
+            .try_fold(len, |len, item| Ok(len + item.lightning_encode(&mut e)?))
+    }
+}
+
+impl<T> LightningDecode for U16Vec<T>
+where
+    T: LightningDecode + Clone,
+{
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let count = u16::lightning_decode(&mut d)? as usize;
+        let mut vec = Vec::with_capacity(prealloc_capacity::<T>(count));
+        for _ in 0..count {
+            vec.push(T::lightning_decode(&mut d)?)
+        }
+        Ok(U16Vec(vec))
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($ty:ident $no:tt),+) => {
+        /// Tuples are encoded as they were fields in the parent data
+        /// structure
+        impl<$($ty),+> LightningEncode for ($($ty),+)
+        where
+            $($ty: LightningEncode),+
+        {
+            fn lightning_encode<E: io::Write>(
+                &self,
+                mut e: E,
+            ) -> Result<usize, Error> {
+                let mut len = 0usize;
+                $( len += self.$no.lightning_encode(&mut e)?; )+
+                Ok(len)
+            }
+        }
+
+        /// Tuples are decoded as they were fields in the parent data
+        /// structure
+        impl<$($ty),+> LightningDecode for ($($ty),+)
+        where
+            $($ty: LightningDecode),+
+        {
+            fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+                Ok(($($ty::lightning_decode(&mut d)?),+))
+            }
+        }
+    };
+}
+
+impl_tuple!(T1 0, T2 1, T3 2);
+impl_tuple!(T1 0, T2 1, T3 2, T4 3);
+impl_tuple!(T1 0, T2 1, T3 2, T4 3, T5 4);
+impl_tuple!(T1 0, T2 1, T3 2, T4 3, T5 4, T6 5);
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use amplify::Slice32;
+    use bitcoin::secp256k1::ecdsa::Signature;
+    use bitcoin::secp256k1::PublicKey;
+
+    use super::*;
+
+    fn pubkey() -> PublicKey {
+        PublicKey::from_str(
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn option_u64() {
+        let none: Option<u64> = None;
+        assert_eq!(none.lightning_serialize().unwrap(), vec![0x00]);
+        assert_eq!(none.lightning_encode(vec![]).unwrap(), 1);
+        assert_eq!(
+            Option::<u64>::lightning_deserialize(&[0x00]).unwrap(),
+            None
+        );
+
+        let some = Some(0xDEADBEEFu64);
+        let ser = some.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x01, 0, 0, 0, 0, 0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(some.lightning_encode(vec![]).unwrap(), 9);
+        assert_eq!(Option::<u64>::lightning_deserialize(&ser).unwrap(), some);
+
+        assert_eq!(
+            Option::<u64>::lightning_deserialize(&[0x02]).unwrap_err(),
+            Error::InvalidOptionFlag(0x02)
+        );
+    }
+
+    #[test]
+    fn option_pubkey() {
+        let pk = pubkey();
+
+        let some = Some(pk);
+        let ser = some.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), 34);
+        assert_eq!(ser[0], 0x01);
+        assert_eq!(&ser[1..], &pk.serialize()[..]);
+        assert_eq!(
+            Option::<PublicKey>::lightning_deserialize(&ser).unwrap(),
+            some
+        );
+
+        let none: Option<PublicKey> = None;
+        let ser = none.lightning_serialize().unwrap();
+        assert_eq!(
+            Option::<PublicKey>::lightning_deserialize(&ser).unwrap(),
+            none
+        );
+    }
+
+    #[test]
+    fn tuple_ints() {
+        let tuple = (0x0102u16, 0x03040506u32, 0x0708090A0B0C0D0Eu64);
+        let ser = tuple.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B,
+            0x0C, 0x0D, 0x0E
+        ]);
+        assert_eq!(tuple.lightning_encode(vec![]).unwrap(), 14);
+        assert_eq!(
+            <(u16, u32, u64)>::lightning_deserialize(&ser).unwrap(),
+            tuple
+        );
+
+        let tuple = (1u8, 2u8, 3u8, 4u8, 5u8, 6u16);
+        let ser = tuple.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![1, 2, 3, 4, 5, 0, 6]);
+        assert_eq!(
+            <(u8, u8, u8, u8, u8, u16)>::lightning_deserialize(&ser).unwrap(),
+            tuple
+        );
+    }
+
+    #[test]
+    fn tuple_slice_pubkey() {
+        let tuple = (Slice32::from_inner([0x11; 32]), pubkey());
+        let ser = tuple.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), 32 + 33);
+        assert_eq!(&ser[..32], &[0x11; 32]);
+        assert_eq!(&ser[32..], &tuple.1.serialize()[..]);
+        assert_eq!(
+            <(Slice32, PublicKey)>::lightning_deserialize(&ser).unwrap(),
+            tuple
+        );
+    }
+
+    #[test]
+    fn vec_oversized_count() {
+        // 0xFFFF items announced, but no data provided
+        assert_eq!(
+            Vec::<u8>::lightning_deserialize(&[0xfd, 0xff, 0xff]).unwrap_err(),
+            Error::from(io::ErrorKind::UnexpectedEof)
+        );
+        assert_eq!(
+            Vec::<PublicKey>::lightning_deserialize(&[0xfd, 0xff, 0xff])
+                .unwrap_err(),
+            Error::from(io::ErrorKind::UnexpectedEof)
+        );
+        // Maximal possible count must not trigger allocation failure
+        assert!(Vec::<u64>::lightning_deserialize(&[0xff; 9]).is_err());
+        assert!(HashMap::<u64, u64>::lightning_deserialize(&[0xff; 9]).is_err());
+    }
+
+    #[test]
+    fn prealloc_limit() {
+        assert_eq!(prealloc_capacity::<u8>(10), 10);
+        assert_eq!(prealloc_capacity::<u8>(usize::MAX), 0xFFFF);
+        assert_eq!(prealloc_capacity::<[u8; 1300]>(usize::MAX), 50);
+        assert_eq!(prealloc_capacity::<()>(usize::MAX), 0xFFFF);
+    }
+
+    #[test]
+    fn hash_set() {
+        let mut set1 = HashSet::new();
+        let mut set2 = HashSet::new();
+        for item in [7u16, 0x0100, 1, 0xFFFF, 42] {
+            set1.insert(item);
+        }
+        for item in [42u16, 0xFFFF, 1, 0x0100, 7] {
+            set2.insert(item);
+        }
+        let ser = set1.lightning_serialize().unwrap();
+        assert_eq!(ser, set2.lightning_serialize().unwrap());
+        assert_eq!(ser, vec![
+            0x05, 0x00, 0x01, 0x00, 0x07, 0x00, 0x2A, 0x01, 0x00, 0xFF, 0xFF
+        ]);
+        assert_eq!(HashSet::<u16>::lightning_deserialize(&ser).unwrap(), set1);
+
+        assert_eq!(
+            HashSet::<u16>::lightning_deserialize(&[
+                0x02, 0x00, 0x07, 0x00, 0x07
+            ])
+            .unwrap_err(),
+            Error::RepeatedKey
+        );
+        assert_eq!(
+            HashSet::<u16>::lightning_deserialize(&[
+                0x02, 0x00, 0x07, 0x00, 0x01
+            ])
+            .unwrap(),
+            HashSet::from([1, 7])
+        );
+    }
+
+    #[test]
+    fn btree_map() {
+        let empty = BTreeMap::<u16, u8>::new();
+        let ser = empty.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x00]);
+        assert_eq!(
+            BTreeMap::<u16, u8>::lightning_deserialize(&ser).unwrap(),
+            empty
+        );
+
+        let single = bmap! { 1u16 => 0xAAu8 };
+        let ser = single.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x01, 0x00, 0x01, 0xAA]);
+        assert_eq!(
+            BTreeMap::<u16, u8>::lightning_deserialize(&ser).unwrap(),
+            single
+        );
+
+        let map = bmap! { 5u16 => 0x05u8, 1u16 => 0x01u8 };
+        let ser = map.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x02, 0x00, 0x01, 0x01, 0x00, 0x05, 0x05]);
+        assert_eq!(
+            BTreeMap::<u16, u8>::lightning_deserialize(&ser).unwrap(),
+            map
+        );
+    }
+
+    #[test]
+    fn btree_map_non_canonical() {
+        assert_eq!(
+            BTreeMap::<u16, u8>::lightning_deserialize(&[
+                0x02, 0x00, 0x01, 0x01, 0x00, 0x01, 0x02
+            ])
+            .unwrap_err(),
+            Error::RepeatedKey
+        );
+        assert_eq!(
+            BTreeMap::<u16, u8>::lightning_deserialize(&[
+                0x02, 0x00, 0x05, 0x05, 0x00, 0x01, 0x01
+            ])
+            .unwrap_err(),
+            Error::UnorderedKeys
+        );
+    }
+
+    #[test]
+    fn u16_vec() {
+        let empty = U16Vec::<u8>::default();
+        assert_eq!(empty.lightning_serialize().unwrap(), vec![0x00, 0x00]);
+
+        let vec = U16Vec::from(vec![0x0102u16, 0x0304u16]);
+        let ser = vec.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x00, 0x02, 0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(U16Vec::<u16>::lightning_deserialize(&ser).unwrap(), vec);
+
+        // Count doesn't match the amount of data
+        assert!(U16Vec::<u16>::lightning_deserialize(&ser[..5]).is_err());
+        assert_eq!(
+            U16Vec::<u16>::lightning_deserialize(&[
+                0x00, 0x01, 0x01, 0x02, 0x03
+            ])
+            .unwrap_err(),
+            Error::DataNotEntirelyConsumed
+        );
+
+        let large = U16Vec::from(vec![0u8; 0x10000]);
+        assert_eq!(
+            large.lightning_serialize().unwrap_err(),
+            Error::TooLargeData(0x10000)
+        );
+    }
+
+    #[test]
+    fn commitment_signed_htlc_signatures() {
+        // channel_id, signature, num_htlcs and three htlc_signature fields
+        let mut data = vec![0x11u8; 32];
+        data.extend([0x22u8; 64]);
+        data.extend([0x00, 0x03]);
+        data.extend([0x33u8; 64]);
+        data.extend([0x44u8; 64]);
+        data.extend([0x55u8; 64]);
+
+        let (channel_id, signature, htlc_signatures) =
+            <(Slice32, Signature, U16Vec<Signature>)>::lightning_deserialize(
+                &data,
+            )
+            .unwrap();
+        assert_eq!(channel_id, Slice32::from_inner([0x11; 32]));
+        assert_eq!(signature.serialize_compact(), [0x22; 64]);
+        assert_eq!(htlc_signatures.as_inner().len(), 3);
+        for (sig, byte) in
+            htlc_signatures.as_inner().iter().zip([0x33, 0x44, 0x55])
+        {
+            assert_eq!(sig.serialize_compact(), [byte; 64]);
+        }
+        assert_eq!(
+            (channel_id, signature, htlc_signatures)
+                .lightning_serialize()
+                .unwrap(),
+            data
+        );
+    }
+}
@@ -12,6 +12,7 @@
 // If not, see <https://opensource.org/licenses/MIT>.
 
 use amplify::IoError;
+use bitcoin::BlockHash;
 use strict_encoding::TlvError;
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error, From)]
@@ -36,6 +37,30 @@ pub enum Error {
     /// not all provided data were consumed during decoding process
     DataNotEntirelyConsumed,
 
+    /// invalid boolean value {0}
+    InvalidBool(u8),
+
+    /// invalid presence flag {0} of an optional value
+    InvalidOptionFlag(u8),
+
+    /// invalid UTF-8 string: invalid byte at position {0}
+    InvalidUtf8(usize),
+
+    /// invalid number of subsecond nanoseconds {0}
+    InvalidDurationNanos(u32),
+
+    /// repeated key in a collection
+    RepeatedKey,
+
+    /// collection keys are not in ascending order
+    UnorderedKeys,
+
+    /// ECDSA signature is not in lower-S form
+    SignatureHighS,
+
+    /// unknown chain with genesis block hash {0}
+    UnknownChain(BlockHash),
+
     /// Custom type-specific error
     #[display(inner)]
     DataIntegrityError(String),
@@ -45,7 +70,7 @@ pub enum Error {
     #[display(inner)]
     Tlv(TlvError),
 
-    /// unsupported value `{0}` for enum `{0}` encountered during decode
+    /// unsupported value `{1}` for enum `{0}` encountered during decode
     /// operation
     EnumValueNotKnown(&'static str, usize),
 
@@ -37,19 +37,33 @@ extern crate amplify;
 mod big_size;
 mod bitcoin;
 mod byte_str;
+mod checksum;
 mod collections;
 mod error;
-// mod net; - no need in encoding network addresses for lightning p2p protocol
+mod net;
 mod primitives;
 pub mod strategies;
+mod tlv;
 
 // -----------------------------------------------------------------------------
 use std::io;
 
 pub use big_size::BigSize;
+pub use checksum::ChecksummedPayload;
+pub use collections::U16Vec;
 pub use error::Error;
 pub use strategies::Strategy;
 pub use strict_encoding::TlvError;
+pub use tlv::TlvStream;
+
+pub use self::bitcoin::{MilliSatoshi, RawScript};
+
+/// Maximum size of a lightning peer message, in bytes, as defined by BOLT-1.
+///
+/// Also used as a ceiling for the memory pre-allocated when decoding
+/// collections from untrusted length prefixes: larger collections are still
+/// decoded, but their buffers grow incrementally as the data are read.
+pub const LIGHTNING_P2P_MAX_MESSAGE_SIZE: usize = 0xFFFF;
 
 /// Lightning-network specific encoding as defined in BOLT-1, 2, 3...
 pub trait LightningEncode {
@@ -76,12 +90,21 @@ where
     /// construct an instance or return implementation-specific error type.
     fn lightning_decode<D: io::Read>(d: D) -> Result<Self, Error>;
 
+    /// Decodes data with [`LightningDecode::lightning_decode`] function,
+    /// returning them together with the number of bytes consumed from the
+    /// reader.
+    fn lightning_decode_with_len<D: io::Read>(
+        d: D,
+    ) -> Result<(Self, usize), Error> {
+        let mut counter = CountingReader::new(d);
+        let rv = Self::lightning_decode(&mut counter)?;
+        Ok((rv, counter.count))
+    }
+
     /// Tries to deserialize byte array into the current type using
     /// [`LightningDecode::lightning_decode`] function.
     fn lightning_deserialize(data: impl AsRef<[u8]>) -> Result<Self, Error> {
-        let mut decoder = io::Cursor::new(data.as_ref());
-        let rv = Self::lightning_decode(&mut decoder)?;
-        let consumed = decoder.position() as usize;
+        let (rv, consumed) = Self::lightning_decode_with_len(data.as_ref())?;
 
         // Fail if data are not consumed entirely.
         if consumed == data.as_ref().len() {
@@ -109,3 +132,56 @@ where
 {
     T::lightning_deserialize(data)
 }
+
+/// Reader counting number of bytes read from the underlying reader
+struct CountingReader<R: io::Read> {
+    inner: R,
+    count: usize,
+}
+
+impl<R: io::Read> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        CountingReader { inner, count: 0 }
+    }
+}
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_with_len() {
+        let data = [0x00u8, 0x02, 0x00, 0x02, 0xAA, 0xBB];
+        assert_eq!(
+            <(u16, U16Vec<u8>)>::lightning_decode_with_len(&data[..]).unwrap(),
+            ((2, U16Vec::from(vec![0xAA, 0xBB])), 6)
+        );
+        assert_eq!(
+            <(u16, Vec<u8>)>::lightning_decode_with_len(&data[1..]).unwrap(),
+            ((0x0200, vec![0xAA, 0xBB]), 5)
+        );
+        assert_eq!(
+            <(u16, u16)>::lightning_decode_with_len(&data[..]).unwrap(),
+            ((2, 2), 4)
+        );
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut data = 0x1234u16.lightning_serialize().unwrap();
+        assert_eq!(u16::lightning_deserialize(&data), Ok(0x1234));
+        data.push(0x00);
+        assert_eq!(
+            u16::lightning_deserialize(&data),
+            Err(Error::DataNotEntirelyConsumed)
+        );
+    }
+}
@@ -11,32 +11,190 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
+//! Encoding of network addresses. IP addresses are prefixed with a single
+//! byte of the address family, using the same values as address descriptor
+//! types in BOLT-7 `node_announcement` message: `1` for IPv4 and `2` for
+//! IPv6. Ports are encoded as big-endian `u16` following the address.
+
+use std::io::{Read, Write};
 use std::net::{
     IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6,
 };
 
-use crate::{strategies, Strategy};
+use crate::{Error, LightningDecode, LightningEncode};
+
+const FAMILY_IPV4: u8 = 1;
+const FAMILY_IPV6: u8 = 2;
+
+impl LightningEncode for Ipv4Addr {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        self.octets().lightning_encode(e)
+    }
+}
+
+impl LightningDecode for Ipv4Addr {
+    #[inline]
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        <[u8; 4]>::lightning_decode(d).map(Ipv4Addr::from)
+    }
+}
+
+impl LightningEncode for Ipv6Addr {
+    #[inline]
+    fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
+        self.octets().lightning_encode(e)
+    }
+}
+
+impl LightningDecode for Ipv6Addr {
+    #[inline]
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        <[u8; 16]>::lightning_decode(d).map(Ipv6Addr::from)
+    }
+}
+
+impl LightningEncode for IpAddr {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        Ok(match self {
+            IpAddr::V4(addr) => {
+                FAMILY_IPV4.lightning_encode(&mut e)?
+                    + addr.lightning_encode(&mut e)?
+            }
+            IpAddr::V6(addr) => {
+                FAMILY_IPV6.lightning_encode(&mut e)?
+                    + addr.lightning_encode(&mut e)?
+            }
+        })
+    }
+}
+
+impl LightningDecode for IpAddr {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        match u8::lightning_decode(&mut d)? {
+            FAMILY_IPV4 => Ipv4Addr::lightning_decode(d).map(IpAddr::V4),
+            FAMILY_IPV6 => Ipv6Addr::lightning_decode(d).map(IpAddr::V6),
+            family => Err(Error::EnumValueNotKnown("IpAddr", family as usize)),
+        }
+    }
+}
 
-impl Strategy for IpAddr {
-    type Strategy = strategies::AsStrict;
+/// Encoded as IPv4 address followed by the port
+impl LightningEncode for SocketAddrV4 {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        Ok(self.ip().lightning_encode(&mut e)?
+            + self.port().lightning_encode(&mut e)?)
+    }
 }
 
-impl Strategy for Ipv4Addr {
-    type Strategy = strategies::AsStrict;
+impl LightningDecode for SocketAddrV4 {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        Ok(SocketAddrV4::new(
+            Ipv4Addr::lightning_decode(&mut d)?,
+            u16::lightning_decode(&mut d)?,
+        ))
+    }
 }
 
-impl Strategy for Ipv6Addr {
-    type Strategy = strategies::AsStrict;
+/// Encoded as IPv6 address followed by the port; flow information and scope
+/// id are not encoded and are set to zero on decoding
+impl LightningEncode for SocketAddrV6 {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        Ok(self.ip().lightning_encode(&mut e)?
+            + self.port().lightning_encode(&mut e)?)
+    }
 }
 
-impl Strategy for SocketAddr {
-    type Strategy = strategies::AsStrict;
+impl LightningDecode for SocketAddrV6 {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        Ok(SocketAddrV6::new(
+            Ipv6Addr::lightning_decode(&mut d)?,
+            u16::lightning_decode(&mut d)?,
+            0,
+            0,
+        ))
+    }
 }
 
-impl Strategy for SocketAddrV4 {
-    type Strategy = strategies::AsStrict;
+impl LightningEncode for SocketAddr {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        Ok(match self {
+            SocketAddr::V4(addr) => {
+                FAMILY_IPV4.lightning_encode(&mut e)?
+                    + addr.lightning_encode(&mut e)?
+            }
+            SocketAddr::V6(addr) => {
+                FAMILY_IPV6.lightning_encode(&mut e)?
+                    + addr.lightning_encode(&mut e)?
+            }
+        })
+    }
 }
 
-impl Strategy for SocketAddrV6 {
-    type Strategy = strategies::AsStrict;
+impl LightningDecode for SocketAddr {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        match u8::lightning_decode(&mut d)? {
+            FAMILY_IPV4 => {
+                SocketAddrV4::lightning_decode(d).map(SocketAddr::V4)
+            }
+            FAMILY_IPV6 => {
+                SocketAddrV6::lightning_decode(d).map(SocketAddr::V6)
+            }
+            family => {
+                Err(Error::EnumValueNotKnown("SocketAddr", family as usize))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn ip_addr() {
+        let addr = IpAddr::from_str("192.168.0.1").unwrap();
+        let ser = addr.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x01, 192, 168, 0, 1]);
+        assert_eq!(IpAddr::lightning_deserialize(&ser).unwrap(), addr);
+
+        let addr = IpAddr::from_str("2001:db8::1").unwrap();
+        let ser = addr.lightning_serialize().unwrap();
+        assert_eq!(ser.len(), 17);
+        assert_eq!(ser[..3], [0x02, 0x20, 0x01]);
+        assert_eq!(IpAddr::lightning_deserialize(&ser).unwrap(), addr);
+    }
+
+    #[test]
+    fn socket_addr_v4() {
+        let addr = SocketAddr::from_str("127.0.0.1:9735").unwrap();
+        let ser = addr.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x01, 127, 0, 0, 1, 0x26, 0x07]);
+        assert_eq!(SocketAddr::lightning_deserialize(&ser).unwrap(), addr);
+    }
+
+    #[test]
+    fn socket_addr_v6() {
+        let addr = SocketAddr::from_str("[::1]:9735").unwrap();
+        let ser = addr.lightning_serialize().unwrap();
+        let mut expected = vec![0x02];
+        expected.extend([0u8; 15]);
+        expected.extend([0x01, 0x26, 0x07]);
+        assert_eq!(ser, expected);
+        assert_eq!(SocketAddr::lightning_deserialize(&ser).unwrap(), addr);
+    }
+
+    #[test]
+    fn unknown_family() {
+        assert_eq!(
+            SocketAddr::lightning_deserialize(&[0x03, 127, 0, 0, 1, 0, 0]),
+            Err(Error::EnumValueNotKnown("SocketAddr", 3))
+        );
+        assert_eq!(
+            IpAddr::lightning_deserialize(&[0x00, 127, 0, 0, 1]),
+            Err(Error::EnumValueNotKnown("IpAddr", 0))
+        );
+    }
 }
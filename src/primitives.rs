@@ -12,6 +12,7 @@
 // If not, see <https://opensource.org/licenses/MIT>.
 
 use std::io::{Read, Write};
+use std::time::Duration;
 
 use amplify::flags::FlagVec;
 use amplify::num::u24;
@@ -20,6 +21,23 @@ use amplify::{Slice32, Wrapper};
 use super::{strategies, Strategy};
 use crate::{BigSize, Error, LightningDecode, LightningEncode};
 
+impl LightningEncode for bool {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        e.write_all(&[*self as u8])?;
+        Ok(1)
+    }
+}
+
+impl LightningDecode for bool {
+    fn lightning_decode<D: Read>(d: D) -> Result<Self, Error> {
+        match u8::lightning_decode(d)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(Error::InvalidBool(other)),
+        }
+    }
+}
+
 impl LightningEncode for u8 {
     fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
         e.write_all(&[*self])?;
@@ -99,6 +117,70 @@ impl LightningDecode for u64 {
     }
 }
 
+impl LightningEncode for i8 {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        let bytes = self.to_be_bytes();
+        e.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl LightningDecode for i8 {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        let mut buf = [0u8; 1];
+        d.read_exact(&mut buf)?;
+        Ok(i8::from_be_bytes(buf))
+    }
+}
+
+impl LightningEncode for i16 {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        let bytes = self.to_be_bytes();
+        e.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl LightningDecode for i16 {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        let mut buf = [0u8; 2];
+        d.read_exact(&mut buf)?;
+        Ok(i16::from_be_bytes(buf))
+    }
+}
+
+impl LightningEncode for i32 {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        let bytes = self.to_be_bytes();
+        e.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl LightningDecode for i32 {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        let mut buf = [0u8; 4];
+        d.read_exact(&mut buf)?;
+        Ok(i32::from_be_bytes(buf))
+    }
+}
+
+impl LightningEncode for i64 {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        let bytes = self.to_be_bytes();
+        e.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl LightningDecode for i64 {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        let mut buf = [0u8; 8];
+        d.read_exact(&mut buf)?;
+        Ok(i64::from_be_bytes(buf))
+    }
+}
+
 impl LightningEncode for usize {
     fn lightning_encode<E: Write>(&self, e: E) -> Result<usize, Error> {
         let size = BigSize::from(*self);
@@ -112,12 +194,23 @@ impl LightningDecode for usize {
     }
 }
 
+/// Feature flags are encoded as a `u16`-prefixed big-endian bit field: the
+/// last byte holds features 0 to 7, with feature 0 in its least significant
+/// bit. Leading zero bytes are omitted.
 impl LightningEncode for FlagVec {
     fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
-        let flags = self.shrunk();
-        let mut vec = flags.as_inner().to_vec();
+        // `FlagVec::shrunk` drops the whole vector when only feature 0 is set,
+        // so zero bytes are trimmed here instead
+        let flags = self.as_inner();
+        let used = flags
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map(|pos| pos + 1)
+            .unwrap_or_default();
+        let mut vec = flags[..used].to_vec();
         vec.reverse();
-        let len = vec.len() as u16;
+        let len = u16::try_from(vec.len())
+            .map_err(|_| Error::TooLargeData(vec.len()))?;
         len.lightning_encode(&mut e)?;
         e.write_all(&vec)?;
         Ok(vec.len() + 2)
@@ -146,6 +239,26 @@ impl LightningDecode for Slice32 {
     }
 }
 
+/// Durations are encoded as the number of whole seconds (`u64`) followed by
+/// the number of nanoseconds within the last second (`u32`).
+impl LightningEncode for Duration {
+    fn lightning_encode<E: Write>(&self, mut e: E) -> Result<usize, Error> {
+        Ok(self.as_secs().lightning_encode(&mut e)?
+            + self.subsec_nanos().lightning_encode(&mut e)?)
+    }
+}
+
+impl LightningDecode for Duration {
+    fn lightning_decode<D: Read>(mut d: D) -> Result<Self, Error> {
+        let secs = u64::lightning_decode(&mut d)?;
+        let nanos = u32::lightning_decode(&mut d)?;
+        if nanos >= 1_000_000_000 {
+            return Err(Error::InvalidDurationNanos(nanos));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
 mod _chrono {
     use chrono::{DateTime, NaiveDateTime, Utc};
 
@@ -159,3 +272,117 @@ mod _chrono {
         type Strategy = strategies::AsStrict;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bool_encoding() {
+        assert_eq!(false.lightning_serialize().unwrap(), vec![0x00]);
+        assert_eq!(true.lightning_serialize().unwrap(), vec![0x01]);
+        assert!(!bool::lightning_deserialize(&[0x00]).unwrap());
+        assert!(bool::lightning_deserialize(&[0x01]).unwrap());
+        assert_eq!(
+            bool::lightning_deserialize(&[0x02]).unwrap_err(),
+            Error::InvalidBool(0x02)
+        );
+        assert_eq!(
+            bool::lightning_deserialize(&[0x01, 0x00]).unwrap_err(),
+            Error::DataNotEntirelyConsumed
+        );
+    }
+
+    #[test]
+    fn signed_integers() {
+        assert_eq!(0i8.lightning_serialize().unwrap(), vec![0x00]);
+        assert_eq!((-1i8).lightning_serialize().unwrap(), vec![0xff]);
+        assert_eq!((-2i16).lightning_serialize().unwrap(), vec![0xff, 0xfe]);
+        assert_eq!(
+            (-0x1234i32).lightning_serialize().unwrap(),
+            (-0x1234i32 as u32).lightning_serialize().unwrap()
+        );
+        assert_eq!(i64::MIN.lightning_serialize().unwrap(), vec![
+            0x80, 0, 0, 0, 0, 0, 0, 0
+        ]);
+        assert_eq!(i64::MAX.lightning_serialize().unwrap(), vec![
+            0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff
+        ]);
+
+        for val in [0i64, -1, -42, i64::MIN, i64::MAX] {
+            let ser = val.lightning_serialize().unwrap();
+            assert_eq!(ser, (val as u64).lightning_serialize().unwrap());
+            assert_eq!(i64::lightning_deserialize(&ser).unwrap(), val);
+        }
+        for val in [0i32, -1, i32::MIN, i32::MAX] {
+            let ser = val.lightning_serialize().unwrap();
+            assert_eq!(i32::lightning_deserialize(&ser).unwrap(), val);
+        }
+        for val in [0i16, -1, i16::MIN, i16::MAX] {
+            let ser = val.lightning_serialize().unwrap();
+            assert_eq!(i16::lightning_deserialize(&ser).unwrap(), val);
+        }
+        for val in [0i8, -1, i8::MIN, i8::MAX] {
+            let ser = val.lightning_serialize().unwrap();
+            assert_eq!(i8::lightning_deserialize(&ser).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn duration() {
+        let ser = Duration::ZERO.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0u8; 12]);
+        assert_eq!(
+            Duration::lightning_deserialize(&ser).unwrap(),
+            Duration::ZERO
+        );
+
+        let subsec = Duration::from_millis(250);
+        let ser = subsec.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0, 0, 0, 0, 0, 0, 0, 0, 0x0E, 0xE6, 0xB2, 0x80]);
+        assert_eq!(Duration::lightning_deserialize(&ser).unwrap(), subsec);
+
+        let days = Duration::new(3 * 24 * 60 * 60, 999_999_999);
+        let ser = days.lightning_serialize().unwrap();
+        assert_eq!(Duration::lightning_deserialize(&ser).unwrap(), days);
+
+        let mut invalid = vec![0u8; 8];
+        invalid.extend(1_000_000_000u32.to_be_bytes());
+        assert_eq!(
+            Duration::lightning_deserialize(&invalid).unwrap_err(),
+            Error::InvalidDurationNanos(1_000_000_000)
+        );
+    }
+
+    #[test]
+    fn flag_vec() {
+        let empty = FlagVec::new();
+        assert_eq!(empty.lightning_serialize().unwrap(), vec![0x00, 0x00]);
+        let decoded = FlagVec::lightning_deserialize(&[0x00, 0x00]).unwrap();
+        assert!(decoded.as_inner().is_empty());
+
+        let mut flags = FlagVec::new();
+        flags.set(0);
+        let ser = flags.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x00, 0x01, 0x01]);
+        let decoded = FlagVec::lightning_deserialize(&ser).unwrap();
+        assert_eq!(decoded.as_inner(), &[0x01]);
+        assert!(decoded.is_set(0));
+
+        flags.set(9);
+        flags.set(31);
+        let ser = flags.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![0x00, 0x04, 0x80, 0x00, 0x02, 0x01]);
+        let decoded = FlagVec::lightning_deserialize(&ser).unwrap();
+        assert_eq!(decoded.as_inner(), &[0x01, 0x02, 0x00, 0x80]);
+
+        // Leading zero bytes are accepted on decoding but not produced
+        let decoded =
+            FlagVec::lightning_deserialize(&[0x00, 0x03, 0x00, 0x00, 0x01])
+                .unwrap();
+        assert!(decoded.is_set(0));
+        assert_eq!(decoded.lightning_serialize().unwrap(), vec![
+            0x00, 0x01, 0x01
+        ]);
+    }
+}
@@ -0,0 +1,256 @@
+// Network encoding for lightning network peer protocol data types
+// Written in 2020-2024 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+
+use amplify::Wrapper;
+
+use crate::collections::prealloc_capacity;
+use crate::{BigSize, Error, LightningDecode, LightningEncode, TlvError};
+
+/// TLV stream: a sequence of type-length-value records, each encoded as
+/// `BigSize` type, `BigSize` value length and the value bytes, ordered by
+/// type.
+///
+/// TLV stream is always the last part of a message, so it is not prefixed
+/// with its length and is decoded up to the end of the data.
+///
+/// TLV stream specification is given at
+/// <https://github.com/lightningnetwork/lightning-rfc/blob/master/01-messaging.md#type-length-value-format>
+#[derive(Wrapper, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct TlvStream(BTreeMap<u64, Box<[u8]>>);
+
+impl From<BTreeMap<u64, Box<[u8]>>> for TlvStream {
+    #[inline]
+    fn from(map: BTreeMap<u64, Box<[u8]>>) -> Self {
+        TlvStream(map)
+    }
+}
+
+impl TlvStream {
+    /// Constructs empty TLV stream
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns number of records in the stream
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Detects whether the stream has no records
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns value of the record with the given type, if present
+    #[inline]
+    pub fn get(&self, type_no: u64) -> Option<&[u8]> {
+        self.0.get(&type_no).map(|value| &value[..])
+    }
+
+    /// Inserts record into the stream, returning the value of a previously
+    /// present record with the same type
+    #[inline]
+    pub fn insert(
+        &mut self,
+        type_no: u64,
+        value: impl Into<Box<[u8]>>,
+    ) -> Option<Box<[u8]>> {
+        self.0.insert(type_no, value.into())
+    }
+
+    /// Removes record with the given type from the stream, returning its value
+    #[inline]
+    pub fn remove(&mut self, type_no: u64) -> Option<Box<[u8]>> {
+        self.0.remove(&type_no)
+    }
+
+    /// Returns iterator over records in ascending type order
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.0.iter().map(|(type_no, value)| (*type_no, &value[..]))
+    }
+
+    /// Applies "it's ok to be odd" rule to the records with types not listed
+    /// in `known_types`: fails with [`TlvError::UnknownEvenType`] on the first
+    /// unknown even type, and otherwise returns all unknown (odd) records so
+    /// they can be preserved.
+    pub fn unknown_odd(
+        &self,
+        known_types: &[u64],
+    ) -> Result<BTreeMap<u64, Box<[u8]>>, TlvError> {
+        let mut unknown = BTreeMap::new();
+        for (type_no, value) in &self.0 {
+            if known_types.contains(type_no) {
+                continue;
+            }
+            if type_no % 2 == 0 {
+                return Err(TlvError::UnknownEvenType(*type_no));
+            }
+            unknown.insert(*type_no, value.clone());
+        }
+        Ok(unknown)
+    }
+}
+
+impl LightningEncode for TlvStream {
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        self.0.iter().try_fold(0usize, |len, (type_no, value)| {
+            let len = len
+                + BigSize::from(*type_no).lightning_encode(&mut e)?
+                + BigSize::from(value.len()).lightning_encode(&mut e)?;
+            e.write_all(value)?;
+            Ok(len + value.len())
+        })
+    }
+}
+
+impl LightningDecode for TlvStream {
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let mut stream = TlvStream::new();
+        let mut max = None;
+        loop {
+            // End of the stream is detected by reading its first byte, such
+            // that any other I/O error is propagated to the caller
+            let mut first = [0u8; 1];
+            match d.read(&mut first) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                    continue
+                }
+                Err(err) => return Err(err.into()),
+            }
+            let type_no =
+                BigSize::lightning_decode((&first[..]).chain(&mut d))?
+                    .into_inner();
+            match max {
+                Some(max) if type_no == max => {
+                    return Err(TlvError::Repeated(type_no).into())
+                }
+                Some(max) if type_no < max => {
+                    return Err(TlvError::Order { read: type_no, max }.into())
+                }
+                _ => max = Some(type_no),
+            }
+
+            let len = BigSize::lightning_decode(&mut d)?.into_inner();
+            let mut value =
+                Vec::with_capacity(prealloc_capacity::<u8>(len as usize));
+            (&mut d).take(len).read_to_end(&mut value)?;
+            if value.len() as u64 != len {
+                return Err(TlvError::Len {
+                    expected: len,
+                    actual: value.len() as u64,
+                }
+                .into());
+            }
+            stream.0.insert(type_no, value.into_boxed_slice());
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty_stream() {
+        let stream = TlvStream::new();
+        assert_eq!(stream.lightning_serialize().unwrap(), Vec::<u8>::new());
+        assert_eq!(TlvStream::lightning_deserialize(&[]).unwrap(), stream);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut stream = TlvStream::new();
+        stream.insert(0x0101, vec![0xAAu8, 0xBB]);
+        stream.insert(1, vec![0u8; 0]);
+        stream.insert(3, vec![0x01u8]);
+        let ser = stream.lightning_serialize().unwrap();
+        assert_eq!(ser, vec![
+            0x01, 0x00, 0x03, 0x01, 0x01, 0xfd, 0x01, 0x01, 0x02, 0xAA, 0xBB
+        ]);
+        let decoded = TlvStream::lightning_deserialize(&ser).unwrap();
+        assert_eq!(decoded, stream);
+        assert_eq!(decoded.get(3), Some(&[0x01u8][..]));
+        assert_eq!(decoded.iter().map(|(t, _)| t).collect::<Vec<_>>(), vec![
+            1, 3, 0x0101
+        ]);
+    }
+
+    #[test]
+    fn ordering() {
+        assert_eq!(
+            TlvStream::lightning_deserialize(&[0x03, 0x00, 0x01, 0x00]),
+            Err(Error::Tlv(TlvError::Order { read: 1, max: 3 }))
+        );
+        assert_eq!(
+            TlvStream::lightning_deserialize(&[0x01, 0x00, 0x01, 0x00]),
+            Err(Error::Tlv(TlvError::Repeated(1)))
+        );
+        assert_eq!(
+            TlvStream::lightning_deserialize(&[0x01, 0x03, 0x00]),
+            Err(Error::Tlv(TlvError::Len {
+                expected: 3,
+                actual: 1
+            }))
+        );
+    }
+
+    #[test]
+    fn io_error() {
+        struct FailingReader;
+        impl io::Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::ConnectionReset.into())
+            }
+        }
+
+        assert_eq!(
+            TlvStream::lightning_decode(FailingReader),
+            Err(io::ErrorKind::ConnectionReset.into())
+        );
+        assert_eq!(
+            TlvStream::lightning_decode(
+                (&[0x01u8, 0x01, 0xAA][..]).chain(FailingReader)
+            ),
+            Err(io::ErrorKind::ConnectionReset.into())
+        );
+    }
+
+    #[test]
+    fn odd_even_rule() {
+        let stream = TlvStream::lightning_deserialize(&[
+            0x01, 0x01, 0xAA, 0x02, 0x00, 0x05, 0x01, 0xBB,
+        ])
+        .unwrap();
+
+        let unknown = stream.unknown_odd(&[2]).unwrap();
+        assert_eq!(unknown.len(), 2);
+        assert_eq!(&unknown[&1][..], &[0xAA]);
+        assert_eq!(&unknown[&5][..], &[0xBB]);
+
+        assert_eq!(
+            stream.unknown_odd(&[1, 5]),
+            Err(TlvError::UnknownEvenType(2))
+        );
+        assert!(stream.unknown_odd(&[1, 2, 5]).unwrap().is_empty());
+    }
+}